@@ -1,4 +1,8 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::compile_protos("proto/key_value.proto")?;
+
+    #[cfg(feature = "raft")]
+    tonic_build::compile_protos("proto/raft.proto")?;
+
     Ok(())
-}
\ No newline at end of file
+}
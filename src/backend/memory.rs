@@ -0,0 +1,316 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+use super::{
+    scan_range_bounds, BatchOperation, BatchOperationOutcome, BatchOutcome, InsertOutcome, KvBackend, Quota,
+    ScanDirection, ScanItem, StoreUsage,
+};
+
+/// In-memory `KvBackend` backed by a `BTreeMap` per store, primarily useful
+/// for tests and for running the server without a sled database on disk.
+#[derive(Default)]
+pub struct MemoryBackend {
+    stores: Mutex<HashMap<String, BTreeMap<Vec<u8>, Vec<u8>>>>,
+    usage: Mutex<HashMap<String, StoreUsage>>,
+    quotas: Mutex<HashMap<String, Quota>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[tonic::async_trait]
+impl KvBackend for MemoryBackend {
+    type Error = Infallible;
+    type ScanStream = tokio_stream::Iter<std::vec::IntoIter<ScanItem<Self::Error>>>;
+
+    async fn get(&self, store: &str, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.stores.lock().unwrap().get(store).and_then(|entries| entries.get(key).cloned()))
+    }
+
+    async fn insert(&self, store: &str, key: &[u8], value: &[u8]) -> Result<InsertOutcome, Self::Error> {
+        let mut stores = self.stores.lock().unwrap();
+        let mut usage = self.usage.lock().unwrap();
+        let quota = self.quotas.lock().unwrap().get(store).copied().unwrap_or_default();
+
+        let entries = stores.entry(store.to_string()).or_default();
+        let old_len = entries.get(key).map(|value| value.len() as u64).unwrap_or(0);
+        let current_usage = usage.get(store).copied().unwrap_or_default();
+
+        let mut projected = current_usage;
+        projected.bytes = projected.bytes.saturating_sub(old_len) + value.len() as u64;
+        if !entries.contains_key(key) {
+            projected.keys += 1;
+        }
+
+        if projected.exceeds(&quota) {
+            return Ok(InsertOutcome::QuotaExceeded { usage: current_usage, quota });
+        }
+
+        entries.insert(key.to_vec(), value.to_vec());
+        usage.insert(store.to_string(), projected);
+        Ok(InsertOutcome::Inserted)
+    }
+
+    async fn remove(&self, store: &str, key: &[u8]) -> Result<(), Self::Error> {
+        if let Some(entries) = self.stores.lock().unwrap().get_mut(store) {
+            if let Some(old) = entries.remove(key) {
+                let mut usage = self.usage.lock().unwrap();
+                let current_usage = usage.get(store).copied().unwrap_or_default();
+                usage.insert(
+                    store.to_string(),
+                    StoreUsage { bytes: current_usage.bytes.saturating_sub(old.len() as u64), keys: current_usage.keys.saturating_sub(1) },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn scan(
+        &self,
+        store: &str,
+        start_key: Vec<u8>,
+        prefix: Vec<u8>,
+        start_inclusive: bool,
+        direction: ScanDirection,
+        limit: usize,
+    ) -> Self::ScanStream {
+        let max_count = if limit == 0 { usize::MAX } else { limit };
+        let stores = self.stores.lock().unwrap();
+        let bounds = scan_range_bounds(&start_key, &prefix, direction);
+
+        let mut matching: Vec<(Vec<u8>, Vec<u8>)> = stores
+            .get(store)
+            .into_iter()
+            .flat_map(|entries| entries.range(bounds.clone()))
+            .filter(|(key, _)| start_inclusive || **key != start_key)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        if direction == ScanDirection::Reverse {
+            matching.reverse();
+        }
+        matching.truncate(max_count);
+
+        let items: Vec<ScanItem<Self::Error>> = matching.into_iter().map(Ok).collect();
+        tokio_stream::iter(items)
+    }
+
+    async fn list_stores(&self) -> Result<Vec<String>, Self::Error> {
+        Ok(self
+            .stores
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, entries)| !name.is_empty() && !entries.is_empty())
+            .map(|(name, _)| name.clone())
+            .collect())
+    }
+
+    async fn drop_store(&self, store: &str) -> Result<(), Self::Error> {
+        self.stores.lock().unwrap().remove(store);
+        self.usage.lock().unwrap().remove(store);
+        self.quotas.lock().unwrap().remove(store);
+        Ok(())
+    }
+
+    async fn batch(&self, store: &str, operations: Vec<BatchOperation>) -> Result<BatchOutcome, Self::Error> {
+        let mut stores = self.stores.lock().unwrap();
+        let mut usage_map = self.usage.lock().unwrap();
+        let quota = self.quotas.lock().unwrap().get(store).copied().unwrap_or_default();
+        let entries = stores.entry(store.to_string()).or_default();
+
+        // Check every precondition against current state before applying
+        // anything, so the whole batch commits or none of it does.
+        for (index, operation) in operations.iter().enumerate() {
+            if let Some(expected) = &operation.precondition {
+                let current = entries.get(&operation.key).cloned();
+                if &current != expected {
+                    let results = operations
+                        .iter()
+                        .enumerate()
+                        .map(|(other_index, _)| BatchOperationOutcome {
+                            applied: false,
+                            current_value: if other_index == index { current.clone() } else { None },
+                        })
+                        .collect();
+                    return Ok(BatchOutcome { applied: false, results, quota_exceeded: None });
+                }
+            }
+        }
+
+        // Apply against a scratch copy first so that, if the batch's
+        // combined effect would exceed the store's quota partway through,
+        // nothing the batch already wrote is visible - either all of it
+        // lands or none of it does.
+        let mut working = entries.clone();
+        let mut usage = usage_map.get(store).copied().unwrap_or_default();
+
+        for operation in &operations {
+            let old = working.get(&operation.key).cloned();
+            let old_len = old.as_ref().map(|value| value.len() as u64).unwrap_or(0);
+
+            match &operation.value {
+                Some(value) => {
+                    let mut projected = usage;
+                    projected.bytes = projected.bytes.saturating_sub(old_len) + value.len() as u64;
+                    if old.is_none() {
+                        projected.keys += 1;
+                    }
+                    if projected.exceeds(&quota) {
+                        return Ok(BatchOutcome {
+                            applied: false,
+                            results: operations.iter().map(|_| BatchOperationOutcome { applied: false, current_value: None }).collect(),
+                            quota_exceeded: Some((usage, quota)),
+                        });
+                    }
+                    working.insert(operation.key.clone(), value.clone());
+                    usage = projected;
+                }
+                None => {
+                    if old.is_some() {
+                        working.remove(&operation.key);
+                        usage.bytes = usage.bytes.saturating_sub(old_len);
+                        usage.keys = usage.keys.saturating_sub(1);
+                    }
+                }
+            }
+        }
+
+        *entries = working;
+        usage_map.insert(store.to_string(), usage);
+
+        Ok(BatchOutcome {
+            applied: true,
+            results: operations.iter().map(|_| BatchOperationOutcome { applied: true, current_value: None }).collect(),
+            quota_exceeded: None,
+        })
+    }
+
+    async fn set_quota(&self, store: &str, quota: Quota) {
+        self.quotas.lock().unwrap().insert(store.to_string(), quota);
+    }
+
+    async fn usage(&self, store: &str) -> Result<(StoreUsage, Quota), Self::Error> {
+        let usage = self.usage.lock().unwrap().get(store).copied().unwrap_or_default();
+        let quota = self.quotas.lock().unwrap().get(store).copied().unwrap_or_default();
+        Ok((usage, quota))
+    }
+
+    async fn repair_quota(&self, store: &str) -> Result<StoreUsage, Self::Error> {
+        let stores = self.stores.lock().unwrap();
+        let recomputed = stores
+            .get(store)
+            .map(|entries| StoreUsage { bytes: entries.values().map(|value| value.len() as u64).sum(), keys: entries.len() as u64 })
+            .unwrap_or_default();
+        self.usage.lock().unwrap().insert(store.to_string(), recomputed);
+        Ok(recomputed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::backend::DEFAULT_STORE;
+
+    async fn collect(backend: &MemoryBackend, start_key: &[u8], prefix: &[u8], start_inclusive: bool, direction: ScanDirection, limit: usize) -> Vec<String> {
+        let mut scan = backend.scan(DEFAULT_STORE, start_key.to_vec(), prefix.to_vec(), start_inclusive, direction, limit).await;
+        let mut keys = Vec::new();
+        while let Some(item) = scan.next().await {
+            let (key, _) = item.unwrap();
+            keys.push(String::from_utf8(key).unwrap());
+        }
+        keys
+    }
+
+    #[tokio::test]
+    async fn forward_scan_resumes_after_the_last_seen_key() {
+        let backend = MemoryBackend::new();
+        for key in ["k1", "k2", "k3", "k4", "k5"] {
+            backend.insert(DEFAULT_STORE, key.as_bytes(), b"v").await.unwrap();
+        }
+
+        let first_page = collect(&backend, b"k1", b"", true, ScanDirection::Forward, 2).await;
+        assert_eq!(first_page, vec!["k1", "k2"]);
+
+        let second_page = collect(&backend, b"k2", b"", false, ScanDirection::Forward, 10).await;
+        assert_eq!(second_page, vec!["k3", "k4", "k5"]);
+    }
+
+    #[tokio::test]
+    async fn reverse_scan_walks_backward_from_start_key() {
+        let backend = MemoryBackend::new();
+        for key in ["k1", "k2", "k3", "k4", "k5"] {
+            backend.insert(DEFAULT_STORE, key.as_bytes(), b"v").await.unwrap();
+        }
+
+        let page = collect(&backend, b"k4", b"", true, ScanDirection::Reverse, 3).await;
+        assert_eq!(page, vec!["k4", "k3", "k2"]);
+    }
+
+    #[tokio::test]
+    async fn scan_combines_prefix_with_a_start_key_inside_the_prefix() {
+        let backend = MemoryBackend::new();
+        for key in ["a1", "k1", "k2", "k3", "z9"] {
+            backend.insert(DEFAULT_STORE, key.as_bytes(), b"v").await.unwrap();
+        }
+
+        let page = collect(&backend, b"k2", b"k", true, ScanDirection::Forward, 10).await;
+        assert_eq!(page, vec!["k2", "k3"]);
+    }
+
+    #[tokio::test]
+    async fn batch_rejects_whole_batch_on_precondition_conflict() {
+        let backend = MemoryBackend::new();
+        backend.insert("orders", b"k1", b"v1").await.unwrap();
+
+        let outcome = backend
+            .batch(
+                "orders",
+                vec![
+                    BatchOperation { key: b"k1".to_vec(), value: Some(b"v2".to_vec()), precondition: Some(Some(b"wrong".to_vec())) },
+                    BatchOperation { key: b"k2".to_vec(), value: Some(b"v".to_vec()), precondition: None },
+                ],
+            )
+            .await
+            .unwrap();
+
+        assert!(!outcome.applied);
+        assert_eq!(backend.get("orders", b"k1").await.unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(backend.get("orders", b"k2").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn batch_rejects_whole_batch_when_quota_would_be_exceeded() {
+        let backend = MemoryBackend::new();
+        backend.set_quota("orders", Quota { max_bytes: None, max_keys: Some(1) }).await;
+        backend.insert("orders", b"k1", b"v").await.unwrap();
+
+        let outcome = backend
+            .batch("orders", vec![BatchOperation { key: b"k2".to_vec(), value: Some(b"v".to_vec()), precondition: None }])
+            .await
+            .unwrap();
+
+        assert!(!outcome.applied);
+        assert!(outcome.quota_exceeded.is_some());
+        assert_eq!(backend.get("orders", b"k2").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn drop_store_clears_usage_and_quota() {
+        let backend = MemoryBackend::new();
+        backend.set_quota("orders", Quota { max_bytes: None, max_keys: Some(1) }).await;
+        backend.insert("orders", b"k1", b"v").await.unwrap();
+
+        backend.drop_store("orders").await.unwrap();
+
+        let (usage, quota) = backend.usage("orders").await.unwrap();
+        assert_eq!(usage, StoreUsage::default());
+        assert_eq!(quota, Quota::default());
+    }
+}
@@ -0,0 +1,260 @@
+use std::ops::Bound;
+
+use tokio_stream::Stream;
+
+mod memory;
+mod sled;
+
+pub use memory::MemoryBackend;
+pub use sled::SledBackend;
+
+/// Name of the default, unnamed store. Callers may also address it by
+/// passing an empty store name to any `KvBackend` method.
+pub const DEFAULT_STORE: &str = "";
+
+/// Ordering in which a range scan is walked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanDirection {
+    Forward,
+    Reverse,
+}
+
+/// A single entry produced while scanning a range, or the backend error that
+/// interrupted the scan.
+pub type ScanItem<E> = Result<(Vec<u8>, Vec<u8>), E>;
+
+/// The first key, if any, that is lexicographically greater than every key
+/// starting with `prefix`. `None` means there is no finite upper bound (the
+/// prefix is empty, or made entirely of `0xFF` bytes).
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+    while let Some(&last) = upper.last() {
+        if last == 0xFF {
+            upper.pop();
+        } else {
+            *upper.last_mut().expect("just checked non-empty") += 1;
+            return Some(upper);
+        }
+    }
+    None
+}
+
+/// Combines `start_key` and `prefix` into the single range a scan should
+/// walk, so a range backend (sled's `Tree::range`, `BTreeMap::range`) only
+/// ever needs one `range()` call instead of separately handling "resume from
+/// here" and "restricted to this prefix".
+///
+/// Every key yielded by the returned bounds is guaranteed to start with
+/// `prefix`, so callers don't need to re-check it per entry.
+pub(crate) fn scan_range_bounds(start_key: &[u8], prefix: &[u8], direction: ScanDirection) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let prefix_upper = prefix_upper_bound(prefix);
+
+    match direction {
+        ScanDirection::Forward => {
+            let lower = if prefix > start_key { prefix.to_vec() } else { start_key.to_vec() };
+            match prefix_upper {
+                // start_key lies past every key with this prefix: walk an empty range.
+                Some(upper) if lower >= upper => (Bound::Included(upper.clone()), Bound::Excluded(upper)),
+                Some(upper) => (Bound::Included(lower), Bound::Excluded(upper)),
+                None => (Bound::Included(lower), Bound::Unbounded),
+            }
+        }
+        ScanDirection::Reverse => {
+            // start_key lies before every key with this prefix: walk an empty range.
+            if !prefix.is_empty() && start_key < prefix {
+                return (Bound::Included(prefix.to_vec()), Bound::Excluded(prefix.to_vec()));
+            }
+            let lower = if prefix.is_empty() { Bound::Unbounded } else { Bound::Included(prefix.to_vec()) };
+            let upper = match prefix_upper {
+                Some(upper) if upper <= start_key.to_vec() => Bound::Excluded(upper),
+                _ => Bound::Included(start_key.to_vec()),
+            };
+            (lower, upper)
+        }
+    }
+}
+
+/// A single write within a `batch` call.
+#[derive(Debug, Clone)]
+pub struct BatchOperation {
+    pub key: Vec<u8>,
+    /// `Some` inserts/overwrites the key with this value, `None` deletes it.
+    pub value: Option<Vec<u8>>,
+    /// Optional compare-and-swap precondition on the key's current value.
+    /// `Some(None)` means "the key must not currently exist".
+    pub precondition: Option<Option<Vec<u8>>>,
+}
+
+/// Outcome of a single operation within a batch.
+#[derive(Debug, Clone)]
+pub struct BatchOperationOutcome {
+    pub applied: bool,
+    /// The key's actual current value, populated when its precondition was
+    /// violated so the caller can see what it conflicted with.
+    pub current_value: Option<Vec<u8>>,
+}
+
+/// Outcome of an entire `batch` call.
+#[derive(Debug, Clone)]
+pub struct BatchOutcome {
+    /// Whether every operation was applied. If any precondition failed or
+    /// the combined effect of the batch would have exceeded the store's
+    /// quota, this is `false` and none of the operations were applied.
+    pub applied: bool,
+    pub results: Vec<BatchOperationOutcome>,
+    /// Set instead of `results` carrying anything useful when the batch was
+    /// rejected because applying it would have pushed the store over its
+    /// quota, so callers can report a useful error.
+    pub quota_exceeded: Option<(StoreUsage, Quota)>,
+}
+
+/// Configurable limits for a single named store. `None` in either field
+/// means that dimension is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub max_bytes: Option<u64>,
+    pub max_keys: Option<u64>,
+}
+
+/// A store's current usage, tracked incrementally as keys are written and
+/// removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreUsage {
+    pub bytes: u64,
+    pub keys: u64,
+}
+
+impl StoreUsage {
+    fn exceeds(&self, quota: &Quota) -> bool {
+        quota.max_bytes.is_some_and(|max| self.bytes > max) || quota.max_keys.is_some_and(|max| self.keys > max)
+    }
+}
+
+/// Outcome of an `insert` call once the store's quota is taken into account.
+#[derive(Debug, Clone)]
+pub enum InsertOutcome {
+    Inserted,
+    /// The insert would have pushed the store over its quota; nothing was
+    /// written. Carries the usage the store is already at and the quota it
+    /// hit, so callers can report a useful error.
+    QuotaExceeded { usage: StoreUsage, quota: Quota },
+}
+
+/// Storage backend abstraction so the gRPC layer isn't tied to sled directly.
+///
+/// Implementors only need to provide point lookups/mutations and a lazy
+/// range scan; the service is written entirely against this trait, so new
+/// backends (Redis, LMDB, ...) can be added without touching the RPC layer.
+///
+/// Every operation is scoped to a named store (namespace); passing
+/// `DEFAULT_STORE` (the empty string) operates on the default, unnamed one.
+#[tonic::async_trait]
+pub trait KvBackend: Send + Sync + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+    type ScanStream: Stream<Item = ScanItem<Self::Error>> + Send + Unpin + 'static;
+
+    async fn get(&self, store: &str, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Insert (or overwrite) a key, rejecting the write with
+    /// `InsertOutcome::QuotaExceeded` instead of applying it if doing so
+    /// would push the store's usage past its configured quota.
+    async fn insert(&self, store: &str, key: &[u8], value: &[u8]) -> Result<InsertOutcome, Self::Error>;
+
+    async fn remove(&self, store: &str, key: &[u8]) -> Result<(), Self::Error>;
+
+    /// Scan entries starting at `start_key`, optionally filtered to `prefix`,
+    /// yielding at most `limit` entries (0 means unbounded).
+    async fn scan(
+        &self,
+        store: &str,
+        start_key: Vec<u8>,
+        prefix: Vec<u8>,
+        start_inclusive: bool,
+        direction: ScanDirection,
+        limit: usize,
+    ) -> Self::ScanStream;
+
+    /// Names of every store that currently has at least one entry, not
+    /// including the default store.
+    async fn list_stores(&self) -> Result<Vec<String>, Self::Error>;
+
+    /// Delete an entire named store and all of its keys.
+    async fn drop_store(&self, store: &str) -> Result<(), Self::Error>;
+
+    /// Apply a list of inserts/deletes atomically: either every operation
+    /// takes effect or none do. If any operation carries a precondition and
+    /// that precondition doesn't hold against the key's current value, the
+    /// whole batch is rejected and no writes are applied.
+    async fn batch(&self, store: &str, operations: Vec<BatchOperation>) -> Result<BatchOutcome, Self::Error>;
+
+    /// Set (or clear, by passing `Quota::default()`) the quota enforced on
+    /// a store's future inserts. Does not retroactively reject a store that
+    /// is already over the new limit.
+    async fn set_quota(&self, store: &str, quota: Quota);
+
+    /// Current usage and configured quota for a store.
+    async fn usage(&self, store: &str) -> Result<(StoreUsage, Quota), Self::Error>;
+
+    /// Recompute a store's usage counters from scratch by scanning its
+    /// contents, correcting for any drift between the incremental counters
+    /// and what's actually stored.
+    async fn repair_quota(&self, store: &str) -> Result<StoreUsage, Self::Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_upper_bound_increments_last_non_ff_byte() {
+        assert_eq!(prefix_upper_bound(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_upper_bound(b"a"), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn prefix_upper_bound_carries_through_trailing_ff_bytes() {
+        assert_eq!(prefix_upper_bound(&[b'a', 0xFF]), Some(vec![b'b']));
+        assert_eq!(prefix_upper_bound(&[0xFF, 0xFF]), None);
+    }
+
+    #[test]
+    fn prefix_upper_bound_of_empty_prefix_is_unbounded() {
+        assert_eq!(prefix_upper_bound(b""), None);
+    }
+
+    #[test]
+    fn forward_scan_without_prefix_starts_at_start_key() {
+        let (lower, upper) = scan_range_bounds(b"k3", b"", ScanDirection::Forward);
+        assert_eq!(lower, Bound::Included(b"k3".to_vec()));
+        assert_eq!(upper, Bound::Unbounded);
+    }
+
+    #[test]
+    fn forward_scan_resumes_inside_prefix_range() {
+        // start_key is already past the prefix's own start, so it should win.
+        let (lower, upper) = scan_range_bounds(b"k3", b"k", ScanDirection::Forward);
+        assert_eq!(lower, Bound::Included(b"k3".to_vec()));
+        assert_eq!(upper, Bound::Excluded(b"l".to_vec()));
+    }
+
+    #[test]
+    fn forward_scan_with_start_key_before_prefix_starts_at_prefix() {
+        let (lower, upper) = scan_range_bounds(b"a", b"k", ScanDirection::Forward);
+        assert_eq!(lower, Bound::Included(b"k".to_vec()));
+        assert_eq!(upper, Bound::Excluded(b"l".to_vec()));
+    }
+
+    #[test]
+    fn reverse_scan_walks_down_to_prefix_start() {
+        let (lower, upper) = scan_range_bounds(b"k9", b"k", ScanDirection::Reverse);
+        assert_eq!(lower, Bound::Included(b"k".to_vec()));
+        assert_eq!(upper, Bound::Included(b"k9".to_vec()));
+    }
+
+    #[test]
+    fn reverse_scan_clamps_start_key_past_prefix_to_prefix_end() {
+        let (lower, upper) = scan_range_bounds(b"z", b"k", ScanDirection::Reverse);
+        assert_eq!(lower, Bound::Included(b"k".to_vec()));
+        assert_eq!(upper, Bound::Excluded(b"l".to_vec()));
+    }
+}
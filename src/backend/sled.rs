@@ -0,0 +1,533 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+
+use ::sled::transaction::{ConflictableTransactionError, Transactional, TransactionError};
+
+use super::{
+    scan_range_bounds, BatchOperation, BatchOperationOutcome, BatchOutcome, InsertOutcome, KvBackend, Quota,
+    ScanDirection, ScanItem, StoreUsage,
+};
+
+// Channel buffer size for the scan background producer task.
+const SCAN_CHANNEL_CAPACITY: usize = 32;
+
+// Tree holding each store's incrementally-maintained usage counters, keyed
+// by store name.
+const QUOTA_USAGE_TREE: &str = "__store_quota_usage";
+
+// Tree holding each store's configured quota, keyed by store name, so quotas
+// survive a restart the same way the usage counters compared against them do.
+const QUOTA_CONFIG_TREE: &str = "__store_quota_config";
+
+fn encode_usage(usage: StoreUsage) -> [u8; 16] {
+    let mut encoded = [0u8; 16];
+    encoded[0..8].copy_from_slice(&usage.bytes.to_be_bytes());
+    encoded[8..16].copy_from_slice(&usage.keys.to_be_bytes());
+    encoded
+}
+
+fn decode_usage(encoded: &[u8]) -> StoreUsage {
+    StoreUsage {
+        bytes: u64::from_be_bytes(encoded[0..8].try_into().expect("usage entry is 16 bytes")),
+        keys: u64::from_be_bytes(encoded[8..16].try_into().expect("usage entry is 16 bytes")),
+    }
+}
+
+// Byte 0 carries which of the two limits are set (sled has no native
+// Option<u64>), followed by the big-endian limits themselves (0 when unset).
+fn encode_quota(quota: Quota) -> [u8; 17] {
+    let mut encoded = [0u8; 17];
+    encoded[0] = (quota.max_bytes.is_some() as u8) | ((quota.max_keys.is_some() as u8) << 1);
+    encoded[1..9].copy_from_slice(&quota.max_bytes.unwrap_or(0).to_be_bytes());
+    encoded[9..17].copy_from_slice(&quota.max_keys.unwrap_or(0).to_be_bytes());
+    encoded
+}
+
+fn decode_quota(encoded: &[u8]) -> Quota {
+    let flags = encoded[0];
+    let max_bytes = u64::from_be_bytes(encoded[1..9].try_into().expect("quota entry is 17 bytes"));
+    let max_keys = u64::from_be_bytes(encoded[9..17].try_into().expect("quota entry is 17 bytes"));
+    Quota { max_bytes: (flags & 0b01 != 0).then_some(max_bytes), max_keys: (flags & 0b10 != 0).then_some(max_keys) }
+}
+
+/// Why a batch's transaction was aborted without applying any of its writes.
+#[derive(Debug)]
+enum BatchAbort {
+    /// Carries which operation's precondition failed, and the value it
+    /// actually found.
+    Precondition { index: usize, current_value: Option<Vec<u8>> },
+    /// The combined effect of the batch would have pushed the store over
+    /// its quota.
+    Quota { usage: StoreUsage, quota: Quota },
+}
+
+// Runs a batch as a single sled transaction spanning both the store's data
+// tree and the shared usage tree: every precondition is checked against the
+// transactional view before any write is applied, then writes are applied
+// one by one, tracking the running usage total and aborting (rolling back
+// everything) the moment it would exceed quota. Either the whole batch
+// commits - data and usage together - or none of it does.
+fn run_batch(
+    tree: &::sled::Tree,
+    usage_tree: &::sled::Tree,
+    store: &str,
+    quota: Quota,
+    operations: &[BatchOperation],
+) -> Result<BatchOutcome, ::sled::Error> {
+    let store_key = store.as_bytes();
+
+    let transacted = (tree, usage_tree).transaction(|(data, usage)| {
+        for (index, operation) in operations.iter().enumerate() {
+            if let Some(expected) = &operation.precondition {
+                let current = data.get(&operation.key)?.map(|value| value.to_vec());
+                if &current != expected {
+                    return Err(ConflictableTransactionError::Abort(BatchAbort::Precondition { index, current_value: current }));
+                }
+            }
+        }
+
+        let mut running_usage = usage.get(store_key)?.map(|encoded| decode_usage(&encoded)).unwrap_or_default();
+
+        for operation in operations {
+            let old = data.get(&operation.key)?;
+            let old_len = old.as_ref().map(|value| value.len() as u64).unwrap_or(0);
+
+            match &operation.value {
+                Some(value) => {
+                    let mut projected = running_usage;
+                    projected.bytes = projected.bytes.saturating_sub(old_len) + value.len() as u64;
+                    if old.is_none() {
+                        projected.keys += 1;
+                    }
+                    if projected.exceeds(&quota) {
+                        return Err(ConflictableTransactionError::Abort(BatchAbort::Quota { usage: running_usage, quota }));
+                    }
+                    data.insert(operation.key.as_slice(), value.as_slice())?;
+                    running_usage = projected;
+                }
+                None => {
+                    if old.is_some() {
+                        data.remove(operation.key.as_slice())?;
+                        running_usage.bytes = running_usage.bytes.saturating_sub(old_len);
+                        running_usage.keys = running_usage.keys.saturating_sub(1);
+                    }
+                }
+            }
+        }
+
+        usage.insert(store_key, &encode_usage(running_usage)[..])?;
+        Ok(())
+    });
+
+    match transacted {
+        Ok(()) => Ok(BatchOutcome {
+            applied: true,
+            results: operations.iter().map(|_| BatchOperationOutcome { applied: true, current_value: None }).collect(),
+            quota_exceeded: None,
+        }),
+        Err(TransactionError::Abort(BatchAbort::Precondition { index: failed_index, current_value })) => Ok(BatchOutcome {
+            applied: false,
+            results: operations
+                .iter()
+                .enumerate()
+                .map(|(index, _)| BatchOperationOutcome {
+                    applied: false,
+                    current_value: if index == failed_index { current_value.clone() } else { None },
+                })
+                .collect(),
+            quota_exceeded: None,
+        }),
+        Err(TransactionError::Abort(BatchAbort::Quota { usage, quota })) => Ok(BatchOutcome {
+            applied: false,
+            results: operations.iter().map(|_| BatchOperationOutcome { applied: false, current_value: None }).collect(),
+            quota_exceeded: Some((usage, quota)),
+        }),
+        Err(TransactionError::Storage(error)) => Err(error),
+    }
+}
+
+/// `KvBackend` implementation backed by a `sled::Db`, with each named store
+/// mapped to its own `sled::Tree` (the default store is the db's unnamed
+/// tree). Opened trees are cached so repeated calls for the same store don't
+/// keep re-opening it.
+pub struct SledBackend {
+    database: ::sled::Db,
+    trees: Mutex<HashMap<String, ::sled::Tree>>,
+    usage: ::sled::Tree,
+    quotas: ::sled::Tree,
+}
+
+impl SledBackend {
+    pub fn new(database: ::sled::Db) -> Self {
+        let usage = database.open_tree(QUOTA_USAGE_TREE).expect("failed to open quota usage tree");
+        let quotas = database.open_tree(QUOTA_CONFIG_TREE).expect("failed to open quota config tree");
+        Self { database, trees: Mutex::new(HashMap::new()), usage, quotas }
+    }
+
+    // Opens (creating if necessary) and caches the tree for `store`. Only
+    // call this from a write path: opening a tree is what makes sled persist
+    // its name forever, so a read for a store nobody has written to yet must
+    // go through `existing_tree` instead and see "no data" rather than
+    // silently bringing the store into existence.
+    fn tree(&self, store: &str) -> Result<::sled::Tree, ::sled::Error> {
+        if store.is_empty() {
+            // `Db` derefs to its default, unnamed `Tree`; clone that handle.
+            return Ok((*self.database).clone());
+        }
+
+        if let Some(tree) = self.trees.lock().unwrap().get(store) {
+            return Ok(tree.clone());
+        }
+
+        let tree = self.database.open_tree(store)?;
+        self.trees.lock().unwrap().insert(store.to_string(), tree.clone());
+        Ok(tree)
+    }
+
+    // Like `tree`, but for read paths: returns `None` instead of creating the
+    // store's tree when it doesn't already exist.
+    fn existing_tree(&self, store: &str) -> Result<Option<::sled::Tree>, ::sled::Error> {
+        if store.is_empty() {
+            return Ok(Some((*self.database).clone()));
+        }
+
+        if let Some(tree) = self.trees.lock().unwrap().get(store) {
+            return Ok(Some(tree.clone()));
+        }
+
+        if !self.database.tree_names().iter().any(|name| name.as_ref() == store.as_bytes()) {
+            return Ok(None);
+        }
+
+        let tree = self.database.open_tree(store)?;
+        self.trees.lock().unwrap().insert(store.to_string(), tree.clone());
+        Ok(Some(tree))
+    }
+
+    fn usage_of(&self, store: &str) -> StoreUsage {
+        self.usage.get(store.as_bytes()).ok().flatten().map(|encoded| decode_usage(&encoded)).unwrap_or_default()
+    }
+
+    fn quota_of(&self, store: &str) -> Quota {
+        self.quotas.get(store.as_bytes()).ok().flatten().map(|encoded| decode_quota(&encoded)).unwrap_or_default()
+    }
+}
+
+#[tonic::async_trait]
+impl KvBackend for SledBackend {
+    type Error = ::sled::Error;
+    type ScanStream = Pin<Box<dyn Stream<Item = ScanItem<Self::Error>> + Send>>;
+
+    async fn get(&self, store: &str, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        match self.existing_tree(store)? {
+            Some(tree) => Ok(tree.get(key)?.map(|value| value.to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    // Checking the quota and updating the usage counter happen in the same
+    // transaction as the write itself, so two concurrent inserts against the
+    // same store can't both read the same "current usage" and both commit,
+    // oversubscribing the quota (the bug run_batch already avoided).
+    async fn insert(&self, store: &str, key: &[u8], value: &[u8]) -> Result<InsertOutcome, Self::Error> {
+        let tree = self.tree(store)?;
+        let usage_tree = self.usage.clone();
+        let quota = self.quota_of(store);
+        let store_key = store.as_bytes().to_vec();
+
+        let transacted = (&tree, &usage_tree).transaction(|(data, usage)| {
+            let old = data.get(key)?;
+            let old_len = old.as_ref().map(|value| value.len() as u64).unwrap_or(0);
+            let current_usage = usage.get(&store_key)?.map(|encoded| decode_usage(&encoded)).unwrap_or_default();
+
+            let mut projected = current_usage;
+            projected.bytes = projected.bytes.saturating_sub(old_len) + value.len() as u64;
+            if old.is_none() {
+                projected.keys += 1;
+            }
+
+            if projected.exceeds(&quota) {
+                return Err(ConflictableTransactionError::Abort(InsertOutcome::QuotaExceeded { usage: current_usage, quota }));
+            }
+
+            data.insert(key, value)?;
+            usage.insert(store_key.as_slice(), &encode_usage(projected)[..])?;
+            Ok(())
+        });
+
+        match transacted {
+            Ok(()) => Ok(InsertOutcome::Inserted),
+            Err(TransactionError::Abort(outcome)) => Ok(outcome),
+            Err(TransactionError::Storage(error)) => Err(error),
+        }
+    }
+
+    async fn remove(&self, store: &str, key: &[u8]) -> Result<(), Self::Error> {
+        let Some(tree) = self.existing_tree(store)? else {
+            return Ok(());
+        };
+        let usage_tree = self.usage.clone();
+        let store_key = store.as_bytes().to_vec();
+
+        let transacted: Result<(), TransactionError<std::convert::Infallible>> = (&tree, &usage_tree).transaction(|(data, usage)| {
+            if let Some(old) = data.remove(key)? {
+                let current_usage = usage.get(&store_key)?.map(|encoded| decode_usage(&encoded)).unwrap_or_default();
+                let updated =
+                    StoreUsage { bytes: current_usage.bytes.saturating_sub(old.len() as u64), keys: current_usage.keys.saturating_sub(1) };
+                usage.insert(store_key.as_slice(), &encode_usage(updated)[..])?;
+            }
+            Ok(())
+        });
+
+        match transacted {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(never)) => match never {},
+            Err(TransactionError::Storage(error)) => Err(error),
+        }
+    }
+
+    // sled's iterators are synchronous, so we drive them from a blocking task
+    // and forward each pair over a channel as it is produced.
+    async fn scan(
+        &self,
+        store: &str,
+        start_key: Vec<u8>,
+        prefix: Vec<u8>,
+        start_inclusive: bool,
+        direction: ScanDirection,
+        limit: usize,
+    ) -> Self::ScanStream {
+        let (tx, rx) = mpsc::channel(SCAN_CHANNEL_CAPACITY);
+
+        let tree = match self.existing_tree(store) {
+            Ok(Some(tree)) => tree,
+            Ok(None) => return Box::pin(ReceiverStream::new(rx)),
+            Err(error) => {
+                let _ = tx.send(Err(error)).await;
+                return Box::pin(ReceiverStream::new(rx));
+            }
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let bounds = scan_range_bounds(&start_key, &prefix, direction);
+            let iter = tree.range(bounds);
+
+            let max_count = if limit == 0 { usize::MAX } else { limit };
+            let mut yielded = 0usize;
+
+            let mut send = |key: ::sled::IVec, value: ::sled::IVec| -> bool {
+                if yielded >= max_count {
+                    return false;
+                }
+                if !start_inclusive && key.as_ref() == start_key.as_slice() {
+                    return true;
+                }
+                yielded += 1;
+                tx.blocking_send(Ok((key.to_vec(), value.to_vec()))).is_ok()
+            };
+
+            if direction == ScanDirection::Reverse {
+                for entry in iter.rev() {
+                    match entry {
+                        Ok((key, value)) => if !send(key, value) { break },
+                        Err(error) => { let _ = tx.blocking_send(Err(error)); break },
+                    }
+                }
+            } else {
+                for entry in iter {
+                    match entry {
+                        Ok((key, value)) => if !send(key, value) { break },
+                        Err(error) => { let _ = tx.blocking_send(Err(error)); break },
+                    }
+                }
+            }
+        });
+
+        Box::pin(ReceiverStream::new(rx))
+    }
+
+    async fn list_stores(&self) -> Result<Vec<String>, Self::Error> {
+        // sled reserves "__sled__default" for the default, unnamed tree; the
+        // rest of our own "__"-prefixed names are internal bookkeeping trees
+        // (quota usage and quota config, and - with the `raft` feature - the log/vote/state
+        // machine trees), never user-facing stores.
+        const INTERNAL_TREE_PREFIX: &[u8] = b"__";
+
+        let mut stores = Vec::new();
+        for name in self.database.tree_names() {
+            if name.as_ref().starts_with(INTERNAL_TREE_PREFIX) {
+                continue;
+            }
+            let Ok(name) = String::from_utf8(name.to_vec()) else { continue };
+            // A store's tree is only opened on its first write (see `tree`),
+            // but can end up empty again after every key is removed; only
+            // report stores that currently have at least one entry.
+            if self.database.open_tree(&name)?.is_empty() {
+                continue;
+            }
+            stores.push(name);
+        }
+        Ok(stores)
+    }
+
+    async fn drop_store(&self, store: &str) -> Result<(), Self::Error> {
+        self.trees.lock().unwrap().remove(store);
+        self.database.drop_tree(store)?;
+        self.usage.remove(store.as_bytes())?;
+        self.quotas.remove(store.as_bytes())?;
+        Ok(())
+    }
+
+    async fn batch(&self, store: &str, operations: Vec<BatchOperation>) -> Result<BatchOutcome, Self::Error> {
+        let tree = self.tree(store)?;
+        let usage_tree = self.usage.clone();
+        let quota = self.quota_of(store);
+        let store = store.to_string();
+
+        tokio::task::spawn_blocking(move || run_batch(&tree, &usage_tree, &store, quota, &operations))
+            .await
+            .expect("batch task panicked")
+    }
+
+    async fn set_quota(&self, store: &str, quota: Quota) {
+        self.quotas.insert(store.as_bytes(), &encode_quota(quota)[..]).expect("failed to persist store quota");
+    }
+
+    async fn usage(&self, store: &str) -> Result<(StoreUsage, Quota), Self::Error> {
+        Ok((self.usage_of(store), self.quota_of(store)))
+    }
+
+    async fn repair_quota(&self, store: &str) -> Result<StoreUsage, Self::Error> {
+        let tree = self.tree(store)?;
+        let mut usage = StoreUsage::default();
+        for entry in tree.iter() {
+            let (_, value) = entry?;
+            usage.bytes += value.len() as u64;
+            usage.keys += 1;
+        }
+        self.usage.insert(store.as_bytes(), &encode_usage(usage)[..])?;
+        Ok(usage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::backend::DEFAULT_STORE;
+
+    fn backend() -> SledBackend {
+        let database = ::sled::Config::new().temporary(true).open().expect("failed to open temporary sled db");
+        SledBackend::new(database)
+    }
+
+    async fn collect(backend: &SledBackend, start_key: &[u8], prefix: &[u8], start_inclusive: bool, direction: ScanDirection, limit: usize) -> Vec<String> {
+        let mut scan = backend.scan(DEFAULT_STORE, start_key.to_vec(), prefix.to_vec(), start_inclusive, direction, limit).await;
+        let mut keys = Vec::new();
+        while let Some(item) = scan.next().await {
+            let (key, _) = item.unwrap();
+            keys.push(String::from_utf8(key).unwrap());
+        }
+        keys
+    }
+
+    #[tokio::test]
+    async fn forward_scan_resumes_after_the_last_seen_key() {
+        let backend = backend();
+        for key in ["k1", "k2", "k3", "k4", "k5"] {
+            backend.insert(DEFAULT_STORE, key.as_bytes(), b"v").await.unwrap();
+        }
+
+        let first_page = collect(&backend, b"k1", b"", true, ScanDirection::Forward, 2).await;
+        assert_eq!(first_page, vec!["k1", "k2"]);
+
+        let second_page = collect(&backend, b"k2", b"", false, ScanDirection::Forward, 10).await;
+        assert_eq!(second_page, vec!["k3", "k4", "k5"]);
+    }
+
+    #[tokio::test]
+    async fn reverse_scan_walks_backward_from_start_key() {
+        let backend = backend();
+        for key in ["k1", "k2", "k3", "k4", "k5"] {
+            backend.insert(DEFAULT_STORE, key.as_bytes(), b"v").await.unwrap();
+        }
+
+        let page = collect(&backend, b"k4", b"", true, ScanDirection::Reverse, 3).await;
+        assert_eq!(page, vec!["k4", "k3", "k2"]);
+    }
+
+    #[tokio::test]
+    async fn scan_combines_prefix_with_a_start_key_inside_the_prefix() {
+        let backend = backend();
+        for key in ["a1", "k1", "k2", "k3", "z9"] {
+            backend.insert(DEFAULT_STORE, key.as_bytes(), b"v").await.unwrap();
+        }
+
+        let page = collect(&backend, b"k2", b"k", true, ScanDirection::Forward, 10).await;
+        assert_eq!(page, vec!["k2", "k3"]);
+    }
+
+    #[tokio::test]
+    async fn list_stores_ignores_stores_with_no_entries() {
+        let backend = backend();
+        backend.insert("orders", b"k1", b"v").await.unwrap();
+        backend.insert("empty", b"k1", b"v").await.unwrap();
+        backend.remove("empty", b"k1").await.unwrap();
+
+        assert_eq!(backend.list_stores().await.unwrap(), vec!["orders".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn reading_an_unknown_store_does_not_create_it() {
+        let backend = backend();
+        assert_eq!(backend.get("unknown", b"k").await.unwrap(), None);
+        assert!(backend.list_stores().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn batch_rejects_whole_batch_when_quota_would_be_exceeded() {
+        let backend = backend();
+        backend.set_quota("orders", Quota { max_bytes: None, max_keys: Some(1) }).await;
+        backend.insert("orders", b"k1", b"v").await.unwrap();
+
+        let outcome = backend
+            .batch("orders", vec![BatchOperation { key: b"k2".to_vec(), value: Some(b"v".to_vec()), precondition: None }])
+            .await
+            .unwrap();
+
+        assert!(!outcome.applied);
+        assert!(outcome.quota_exceeded.is_some());
+        assert_eq!(backend.get("orders", b"k2").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn drop_store_clears_usage_and_quota() {
+        let backend = backend();
+        backend.set_quota("orders", Quota { max_bytes: None, max_keys: Some(1) }).await;
+        backend.insert("orders", b"k1", b"v").await.unwrap();
+
+        backend.drop_store("orders").await.unwrap();
+
+        let (usage, quota) = backend.usage("orders").await.unwrap();
+        assert_eq!(usage, StoreUsage::default());
+        assert_eq!(quota, Quota::default());
+    }
+
+    #[tokio::test]
+    async fn quota_survives_reopening_the_database() {
+        let database = ::sled::Config::new().temporary(true).open().expect("failed to open temporary sled db");
+
+        let backend = SledBackend::new(database.clone());
+        backend.set_quota("orders", Quota { max_bytes: None, max_keys: Some(1) }).await;
+
+        let reopened = SledBackend::new(database);
+        let (_, quota) = reopened.usage("orders").await.unwrap();
+        assert_eq!(quota, Quota { max_bytes: None, max_keys: Some(1) });
+    }
+}
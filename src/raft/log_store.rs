@@ -0,0 +1,125 @@
+use openraft::storage::{LogState, RaftLogReader, RaftLogStorage};
+use openraft::{Entry, LogId, StorageError, Vote};
+
+use super::{NodeId, TypeConfig};
+
+const LOGS_TREE: &str = "__raft_logs";
+const VOTE_TREE: &str = "__raft_vote";
+const VOTE_KEY: &[u8] = b"vote";
+// Stored alongside the vote rather than in the logs tree, since log entries
+// there are keyed by an 8-byte big-endian index and this key isn't one.
+const LAST_PURGED_KEY: &[u8] = b"last_purged";
+
+fn log_key(index: u64) -> [u8; 8] {
+    index.to_be_bytes()
+}
+
+/// `RaftLogStorage`/`RaftLogReader` implementation on top of sled, following
+/// openraft's `sledstore` example: log entries and the current vote each
+/// live in their own tree, keyed by big-endian log index so range scans
+/// stay in log order.
+#[derive(Clone)]
+pub struct LogStore {
+    logs: sled::Tree,
+    vote: sled::Tree,
+}
+
+impl LogStore {
+    pub fn new(database: &sled::Db) -> Result<Self, sled::Error> {
+        Ok(Self { logs: database.open_tree(LOGS_TREE)?, vote: database.open_tree(VOTE_TREE)? })
+    }
+}
+
+#[openraft::async_trait::async_trait]
+impl RaftLogReader<TypeConfig> for LogStore {
+    async fn try_get_log_entries<RB: std::ops::RangeBounds<u64> + Clone + Send + Sync>(
+        &mut self,
+        range: RB,
+    ) -> Result<Vec<Entry<TypeConfig>>, StorageError<NodeId>> {
+        let mut entries = Vec::new();
+        for item in self.logs.iter() {
+            let (key, value) = item.map_err(|error| StorageError::read_logs(&error))?;
+            let index = u64::from_be_bytes(key.as_ref().try_into().expect("log key is 8 bytes"));
+            if !range.contains(&index) {
+                continue;
+            }
+            let entry: Entry<TypeConfig> = bincode::deserialize(&value).map_err(|error| StorageError::read_logs(&error))?;
+            entries.push(entry);
+        }
+        entries.sort_by_key(|entry| entry.log_id.index);
+        Ok(entries)
+    }
+}
+
+#[openraft::async_trait::async_trait]
+impl RaftLogStorage<TypeConfig> for LogStore {
+    type LogReader = Self;
+
+    async fn get_log_state(&mut self) -> Result<LogState<TypeConfig>, StorageError<NodeId>> {
+        let last_purged_log_id = match self.vote.get(LAST_PURGED_KEY).map_err(|error| StorageError::read_logs(&error))? {
+            Some(bytes) => Some(bincode::deserialize(&bytes).map_err(|error| StorageError::read_logs(&error))?),
+            None => None,
+        };
+
+        let last_log_id = self
+            .logs
+            .last()
+            .map_err(|error| StorageError::read_logs(&error))?
+            .map(|(_, value)| bincode::deserialize::<Entry<TypeConfig>>(&value).unwrap().log_id)
+            // Once every entry has been purged, the logs tree is empty and
+            // the last known log id is whatever was last purged.
+            .or(last_purged_log_id);
+
+        Ok(LogState { last_purged_log_id, last_log_id })
+    }
+
+    async fn save_vote(&mut self, vote: &Vote<NodeId>) -> Result<(), StorageError<NodeId>> {
+        let encoded = bincode::serialize(vote).map_err(|error| StorageError::write_vote(&error))?;
+        self.vote.insert(VOTE_KEY, encoded).map_err(|error| StorageError::write_vote(&error))?;
+        Ok(())
+    }
+
+    async fn read_vote(&mut self) -> Result<Option<Vote<NodeId>>, StorageError<NodeId>> {
+        match self.vote.get(VOTE_KEY).map_err(|error| StorageError::read_vote(&error))? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes).map_err(|error| StorageError::read_vote(&error))?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn append<I>(&mut self, entries: I, callback: impl openraft::storage::LogFlushed<TypeConfig>) -> Result<(), StorageError<NodeId>>
+    where
+        I: IntoIterator<Item = Entry<TypeConfig>> + Send,
+    {
+        for entry in entries {
+            let encoded = bincode::serialize(&entry).map_err(|error| StorageError::write_logs(&error))?;
+            self.logs.insert(log_key(entry.log_id.index), encoded).map_err(|error| StorageError::write_logs(&error))?;
+        }
+        callback.log_io_completed(Ok(()));
+        Ok(())
+    }
+
+    async fn truncate(&mut self, log_id: LogId<NodeId>) -> Result<(), StorageError<NodeId>> {
+        let from = log_key(log_id.index);
+        for item in self.logs.range(from.to_vec()..) {
+            let (key, _) = item.map_err(|error| StorageError::write_logs(&error))?;
+            self.logs.remove(key).map_err(|error| StorageError::write_logs(&error))?;
+        }
+        Ok(())
+    }
+
+    async fn purge(&mut self, log_id: LogId<NodeId>) -> Result<(), StorageError<NodeId>> {
+        let to = log_key(log_id.index + 1);
+        for item in self.logs.range(..to.to_vec()) {
+            let (key, _) = item.map_err(|error| StorageError::write_logs(&error))?;
+            self.logs.remove(key).map_err(|error| StorageError::write_logs(&error))?;
+        }
+
+        let encoded = bincode::serialize(&log_id).map_err(|error| StorageError::write_logs(&error))?;
+        self.vote.insert(LAST_PURGED_KEY, encoded).map_err(|error| StorageError::write_logs(&error))?;
+        Ok(())
+    }
+
+    async fn get_log_reader(&mut self) -> Self::LogReader {
+        self.clone()
+    }
+}
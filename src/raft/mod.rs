@@ -0,0 +1,96 @@
+//! Optional Raft-replicated mode, built on `openraft` on top of sled,
+//! modeled on openraft's `sledstore` example: log entries, vote/hard state,
+//! and the applied state machine each live in their own `sled::Tree`.
+//!
+//! The rest of the server is unaffected when this feature is off: the
+//! `KeyValueStorageService` still talks to a plain `KvBackend`. When it's
+//! on, `RaftNode` sits in front of a backend, proposes every insert and
+//! delete through `openraft`, and only answers once the write is committed.
+
+mod log_store;
+mod network;
+mod service;
+mod state_machine;
+
+pub use log_store::LogStore;
+pub use network::{RaftNetworkFactoryImpl, RaftNetworkImpl};
+pub use service::RaftInternalService;
+pub use state_machine::StateMachineStore;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::backend::SledBackend;
+
+pub type NodeId = u64;
+
+/// Parses a `RAFT_MEMBERS` value: a comma-separated list of
+/// `<node_id>=<host:port>` entries describing every node in the cluster
+/// (including this one), used to seed membership the first time a node boots.
+pub fn parse_members(raw: &str) -> Result<BTreeMap<NodeId, openraft::BasicNode>, Box<dyn std::error::Error>> {
+    let mut members = BTreeMap::new();
+    for entry in raw.split(',') {
+        let (id, addr) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("invalid RAFT_MEMBERS entry: '{entry}', expected <node_id>=<host:port>"))?;
+        members.insert(id.parse::<NodeId>()?, openraft::BasicNode::new(addr));
+    }
+    Ok(members)
+}
+
+openraft::declare_raft_types!(
+    /// Raft type configuration for this server: node ids are `u64`, writes
+    /// are `KvRequest`s, and reads get back a `KvResponse`.
+    pub TypeConfig:
+        D = KvRequest,
+        R = KvResponse,
+        NodeId = NodeId,
+        Node = openraft::BasicNode,
+);
+
+pub type Raft = openraft::Raft<TypeConfig>;
+
+/// A write proposed through Raft. Mirrors the single-key operations the
+/// plain `KvBackend` exposes, so applying a committed entry is just a call
+/// into the backend.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum KvRequest {
+    Insert { store: String, key: Vec<u8>, value: Vec<u8> },
+    Delete { store: String, key: Vec<u8> },
+}
+
+/// Result of applying a `KvRequest` to the state machine.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct KvResponse {
+    pub previous_value: Option<Vec<u8>>,
+    /// Set when an `Insert` was applied as a no-op because it would have
+    /// pushed the store over its configured quota. The write is still
+    /// committed to the Raft log (the cluster already agreed on it), but the
+    /// state machine's data is left unchanged.
+    pub quota_exceeded: bool,
+}
+
+/// Wraps a running `Raft` instance plus the backend its state machine
+/// applies writes to. `insert`/`delete` propose through Raft and only
+/// return once the write is committed; reads go straight to the backend,
+/// which always reflects the committed state machine.
+pub struct RaftNode {
+    pub raft: Raft,
+    pub backend: Arc<SledBackend>,
+}
+
+impl RaftNode {
+    pub fn new(raft: Raft, backend: Arc<SledBackend>) -> Self {
+        Self { raft, backend }
+    }
+
+    pub async fn insert(&self, store: String, key: Vec<u8>, value: Vec<u8>) -> Result<KvResponse, openraft::error::RaftError<NodeId>> {
+        let response = self.raft.client_write(KvRequest::Insert { store, key, value }).await?;
+        Ok(response.data)
+    }
+
+    pub async fn delete(&self, store: String, key: Vec<u8>) -> Result<KvResponse, openraft::error::RaftError<NodeId>> {
+        let response = self.raft.client_write(KvRequest::Delete { store, key }).await?;
+        Ok(response.data)
+    }
+}
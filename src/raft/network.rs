@@ -0,0 +1,99 @@
+use openraft::error::{InstallSnapshotError, NetworkError, RPCError, RaftError};
+use openraft::network::{RaftNetwork, RaftNetworkFactory};
+use openraft::raft::{
+    AppendEntriesRequest, AppendEntriesResponse, InstallSnapshotRequest, InstallSnapshotResponse, VoteRequest,
+    VoteResponse,
+};
+use openraft::BasicNode;
+
+use crate::raft_proto::raft_internal_client::RaftInternalClient;
+use crate::raft_proto::RaftMessage;
+
+use super::{NodeId, TypeConfig};
+
+/// The peer a `client()` connection attempt failed to reach, carried along
+/// with the underlying transport error so network errors are traceable back
+/// to a node id instead of just an address.
+#[derive(Debug)]
+struct PeerConnectError {
+    target: NodeId,
+    source: tonic::transport::Error,
+}
+
+impl std::fmt::Display for PeerConnectError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "connecting to raft peer {}: {}", self.target, self.source)
+    }
+}
+
+impl std::error::Error for PeerConnectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Dials peers over gRPC and forwards openraft's internal RPCs to them,
+/// encoding each request/response as bincode inside a `RaftMessage`.
+#[derive(Clone)]
+pub struct RaftNetworkImpl {
+    target: NodeId,
+    address: String,
+}
+
+impl RaftNetworkImpl {
+    async fn client(&self) -> Result<RaftInternalClient<tonic::transport::Channel>, NetworkError> {
+        RaftInternalClient::connect(self.address.clone())
+            .await
+            .map_err(|error| NetworkError::new(&PeerConnectError { target: self.target, source: error }))
+    }
+}
+
+#[openraft::async_trait::async_trait]
+impl RaftNetwork<TypeConfig> for RaftNetworkImpl {
+    async fn append_entries(
+        &mut self,
+        request: AppendEntriesRequest<TypeConfig>,
+        _option: openraft::network::RPCOption,
+    ) -> Result<AppendEntriesResponse<NodeId>, RPCError<NodeId, BasicNode, RaftError<NodeId>>> {
+        let payload = bincode::serialize(&request).map_err(|error| RPCError::Network(NetworkError::new(&error)))?;
+        let mut client = self.client().await.map_err(RPCError::Network)?;
+        let response = client.append_entries(RaftMessage { payload }).await.map_err(|error| RPCError::Network(NetworkError::new(&error)))?;
+        bincode::deserialize(&response.into_inner().payload).map_err(|error| RPCError::Network(NetworkError::new(&error)))
+    }
+
+    async fn install_snapshot(
+        &mut self,
+        request: InstallSnapshotRequest<TypeConfig>,
+        _option: openraft::network::RPCOption,
+    ) -> Result<InstallSnapshotResponse<NodeId>, RPCError<NodeId, BasicNode, RaftError<NodeId, InstallSnapshotError>>> {
+        let payload = bincode::serialize(&request).map_err(|error| RPCError::Network(NetworkError::new(&error)))?;
+        let mut client = self.client().await.map_err(RPCError::Network)?;
+        let response = client.install_snapshot(RaftMessage { payload }).await.map_err(|error| RPCError::Network(NetworkError::new(&error)))?;
+        bincode::deserialize(&response.into_inner().payload).map_err(|error| RPCError::Network(NetworkError::new(&error)))
+    }
+
+    async fn vote(
+        &mut self,
+        request: VoteRequest<NodeId>,
+        _option: openraft::network::RPCOption,
+    ) -> Result<VoteResponse<NodeId>, RPCError<NodeId, BasicNode, RaftError<NodeId>>> {
+        let payload = bincode::serialize(&request).map_err(|error| RPCError::Network(NetworkError::new(&error)))?;
+        let mut client = self.client().await.map_err(RPCError::Network)?;
+        let response = client.vote(RaftMessage { payload }).await.map_err(|error| RPCError::Network(NetworkError::new(&error)))?;
+        bincode::deserialize(&response.into_inner().payload).map_err(|error| RPCError::Network(NetworkError::new(&error)))
+    }
+}
+
+/// Builds a `RaftNetworkImpl` per target node from the cluster's static
+/// node list (`BasicNode::addr` is the peer's gRPC endpoint).
+#[derive(Clone, Default)]
+pub struct RaftNetworkFactoryImpl;
+
+#[openraft::async_trait::async_trait]
+impl RaftNetworkFactory<TypeConfig> for RaftNetworkFactoryImpl {
+    type Network = RaftNetworkImpl;
+
+    async fn new_client(&mut self, target: NodeId, node: &BasicNode) -> Self::Network {
+        RaftNetworkImpl { target, address: format!("http://{}", node.addr) }
+    }
+}
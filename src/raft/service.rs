@@ -0,0 +1,46 @@
+use tonic::{Request, Response, Status};
+
+use crate::raft_proto::raft_internal_server::RaftInternal;
+use crate::raft_proto::RaftMessage;
+
+use super::Raft;
+
+/// Tonic service that decodes each `RaftMessage`'s bincode payload and hands
+/// it to the local `Raft` instance, so a cluster of these servers can
+/// replicate the keyspace over plain gRPC.
+pub struct RaftInternalService {
+    raft: Raft,
+}
+
+impl RaftInternalService {
+    pub fn new(raft: Raft) -> Self {
+        Self { raft }
+    }
+}
+
+#[tonic::async_trait]
+impl RaftInternal for RaftInternalService {
+    async fn append_entries(&self, request: Request<RaftMessage>) -> Result<Response<RaftMessage>, Status> {
+        let decoded = bincode::deserialize(&request.into_inner().payload)
+            .map_err(|error| Status::invalid_argument(format!("malformed append_entries payload: {error}")))?;
+        let response = self.raft.append_entries(decoded).await.map_err(|error| Status::internal(error.to_string()))?;
+        let payload = bincode::serialize(&response).map_err(|error| Status::internal(error.to_string()))?;
+        Ok(Response::new(RaftMessage { payload }))
+    }
+
+    async fn vote(&self, request: Request<RaftMessage>) -> Result<Response<RaftMessage>, Status> {
+        let decoded = bincode::deserialize(&request.into_inner().payload)
+            .map_err(|error| Status::invalid_argument(format!("malformed vote payload: {error}")))?;
+        let response = self.raft.vote(decoded).await.map_err(|error| Status::internal(error.to_string()))?;
+        let payload = bincode::serialize(&response).map_err(|error| Status::internal(error.to_string()))?;
+        Ok(Response::new(RaftMessage { payload }))
+    }
+
+    async fn install_snapshot(&self, request: Request<RaftMessage>) -> Result<Response<RaftMessage>, Status> {
+        let decoded = bincode::deserialize(&request.into_inner().payload)
+            .map_err(|error| Status::invalid_argument(format!("malformed install_snapshot payload: {error}")))?;
+        let response = self.raft.install_snapshot(decoded).await.map_err(|error| Status::internal(error.to_string()))?;
+        let payload = bincode::serialize(&response).map_err(|error| Status::internal(error.to_string()))?;
+        Ok(Response::new(RaftMessage { payload }))
+    }
+}
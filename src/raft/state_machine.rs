@@ -0,0 +1,151 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use openraft::storage::{RaftSnapshotBuilder, RaftStateMachine, Snapshot};
+use openraft::{Entry, EntryPayload, LogId, OptionalSend, SnapshotMeta, StorageError, StoredMembership};
+
+use crate::backend::{InsertOutcome, KvBackend, SledBackend};
+
+use super::{KvRequest, KvResponse, NodeId, TypeConfig};
+
+const META_TREE: &str = "__raft_state_machine";
+const LAST_APPLIED_KEY: &[u8] = b"last_applied";
+const MEMBERSHIP_KEY: &[u8] = b"membership";
+
+/// Applies committed `KvRequest`s to the shared `SledBackend`, and tracks
+/// the bookkeeping (last applied log id, membership) openraft needs in a
+/// dedicated tree, mirroring the `sledstore` example's state machine.
+#[derive(Clone)]
+pub struct StateMachineStore {
+    meta: sled::Tree,
+    backend: Arc<SledBackend>,
+}
+
+impl StateMachineStore {
+    pub fn new(database: &sled::Db, backend: Arc<SledBackend>) -> Result<Self, sled::Error> {
+        Ok(Self { meta: database.open_tree(META_TREE)?, backend })
+    }
+
+    fn read_last_applied(&self) -> Option<LogId<NodeId>> {
+        self.meta.get(LAST_APPLIED_KEY).ok().flatten().map(|bytes| bincode::deserialize(&bytes).unwrap())
+    }
+
+    fn read_membership(&self) -> StoredMembership<NodeId, openraft::BasicNode> {
+        self.meta
+            .get(MEMBERSHIP_KEY)
+            .ok()
+            .flatten()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap())
+            .unwrap_or_default()
+    }
+}
+
+#[openraft::async_trait::async_trait]
+impl RaftSnapshotBuilder<TypeConfig> for StateMachineStore {
+    // A snapshot is a full dump of the default store plus every named store,
+    // so a node catching up via `install_snapshot` ends up with the same
+    // stores the snapshotting node has, not just its default one.
+    async fn build_snapshot(&mut self) -> Result<Snapshot<TypeConfig>, StorageError<NodeId>> {
+        use tokio_stream::StreamExt;
+
+        let mut entries: Vec<(String, Vec<u8>, Vec<u8>)> = Vec::new();
+
+        let mut stores = vec![crate::backend::DEFAULT_STORE.to_string()];
+        stores.extend(self.backend.list_stores().await.map_err(|error| StorageError::read_snapshot(None, &error))?);
+
+        for store in stores {
+            let mut scan = self.backend.scan(&store, Vec::new(), Vec::new(), true, crate::backend::ScanDirection::Forward, 0).await;
+            while let Some(item) = scan.next().await {
+                let (key, value) = item.map_err(|error| StorageError::read_snapshot(None, &error))?;
+                entries.push((store.clone(), key, value));
+            }
+        }
+        let data = bincode::serialize(&entries).map_err(|error| StorageError::read_snapshot(None, &error))?;
+
+        let last_applied = self.read_last_applied();
+        let membership = self.read_membership();
+        let meta = SnapshotMeta { last_log_id: last_applied, last_membership: membership, snapshot_id: format!("{:?}", last_applied) };
+
+        Ok(Snapshot { meta, snapshot: Box::new(Cursor::new(data)) })
+    }
+}
+
+#[openraft::async_trait::async_trait]
+impl RaftStateMachine<TypeConfig> for StateMachineStore {
+    type SnapshotBuilder = Self;
+
+    async fn applied_state(&mut self) -> Result<(Option<LogId<NodeId>>, StoredMembership<NodeId, openraft::BasicNode>), StorageError<NodeId>> {
+        Ok((self.read_last_applied(), self.read_membership()))
+    }
+
+    async fn apply<I>(&mut self, entries: I) -> Result<Vec<KvResponse>, StorageError<NodeId>>
+    where
+        I: IntoIterator<Item = Entry<TypeConfig>> + OptionalSend,
+    {
+        let mut responses = Vec::new();
+
+        for entry in entries {
+            let response = match entry.payload {
+                EntryPayload::Blank => KvResponse::default(),
+                EntryPayload::Normal(KvRequest::Insert { store, key, value }) => {
+                    let previous_value = self.backend.get(&store, &key).await.map_err(|error| StorageError::write(&error))?;
+                    // A quota-exceeded insert is applied as a no-op: the log
+                    // entry is already committed cluster-wide by this point,
+                    // so the only thing left to decide is whether this
+                    // state machine's data actually changes. Either way the
+                    // response is reported back, so the caller can surface a
+                    // resource-exhausted error instead of a false success.
+                    match self.backend.insert(&store, &key, &value).await.map_err(|error| StorageError::write(&error))? {
+                        InsertOutcome::Inserted => KvResponse { previous_value, quota_exceeded: false },
+                        InsertOutcome::QuotaExceeded { .. } => KvResponse { previous_value: None, quota_exceeded: true },
+                    }
+                }
+                EntryPayload::Normal(KvRequest::Delete { store, key }) => {
+                    let previous_value = self.backend.get(&store, &key).await.map_err(|error| StorageError::write(&error))?;
+                    self.backend.remove(&store, &key).await.map_err(|error| StorageError::write(&error))?;
+                    KvResponse { previous_value, quota_exceeded: false }
+                }
+                EntryPayload::Membership(membership) => {
+                    let stored = StoredMembership::new(Some(entry.log_id), membership);
+                    let encoded = bincode::serialize(&stored).map_err(|error| StorageError::write(&error))?;
+                    self.meta.insert(MEMBERSHIP_KEY, encoded).map_err(|error| StorageError::write(&error))?;
+                    KvResponse::default()
+                }
+            };
+
+            let encoded = bincode::serialize(&entry.log_id).map_err(|error| StorageError::write(&error))?;
+            self.meta.insert(LAST_APPLIED_KEY, encoded).map_err(|error| StorageError::write(&error))?;
+            responses.push(response);
+        }
+
+        Ok(responses)
+    }
+
+    async fn get_snapshot_builder(&mut self) -> Self::SnapshotBuilder {
+        self.clone()
+    }
+
+    async fn begin_receiving_snapshot(&mut self) -> Result<Box<Cursor<Vec<u8>>>, StorageError<NodeId>> {
+        Ok(Box::new(Cursor::new(Vec::new())))
+    }
+
+    async fn install_snapshot(&mut self, meta: &SnapshotMeta<NodeId, openraft::BasicNode>, snapshot: Box<Cursor<Vec<u8>>>) -> Result<(), StorageError<NodeId>> {
+        let entries: Vec<(String, Vec<u8>, Vec<u8>)> =
+            bincode::deserialize(snapshot.get_ref()).map_err(|error| StorageError::read_snapshot(Some(meta.signature()), &error))?;
+
+        for (store, key, value) in entries {
+            self.backend.insert(&store, &key, &value).await.map_err(|error| StorageError::write(&error))?;
+        }
+
+        let encoded = bincode::serialize(&meta.last_log_id).map_err(|error| StorageError::write(&error))?;
+        self.meta.insert(LAST_APPLIED_KEY, encoded).map_err(|error| StorageError::write(&error))?;
+        let encoded = bincode::serialize(&meta.last_membership).map_err(|error| StorageError::write(&error))?;
+        self.meta.insert(MEMBERSHIP_KEY, encoded).map_err(|error| StorageError::write(&error))?;
+
+        Ok(())
+    }
+
+    async fn get_current_snapshot(&mut self) -> Result<Option<Snapshot<TypeConfig>>, StorageError<NodeId>> {
+        Ok(None)
+    }
+}
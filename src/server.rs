@@ -1,34 +1,79 @@
+use std::pin::Pin;
+#[cfg(feature = "raft")]
+use std::sync::Arc;
+
+use tokio_stream::{Stream, StreamExt};
 use tonic::{transport::Server, Request, Response, Status};
+
+use crate::backend::{BatchOperation, InsertOutcome, KvBackend, Quota, ScanDirection, SledBackend};
 use crate::key_value::key_value_storage_server::{KeyValueStorage, KeyValueStorageServer};
-use crate::key_value::{KeyRequest, KeyValuePair, KeysRequest, KeysResponse};
+use crate::key_value::{
+    BatchOperationResult, BatchRequest, BatchResponse, Direction, DropStoreRequest, DropStoreResponse, KeyRequest,
+    KeyValuePair, KeysRequest, KeysResponse, ListStoresRequest, ListStoresResponse, RepairStoreQuotaRequest,
+    ScanRequest, SetStoreQuotaRequest, SetStoreQuotaResponse, StatsRequest, StoreStats,
+};
 
 // Load proto3 definitions
 pub mod key_value {
     tonic::include_proto!("key_value");
 }
 
-// Define the structure of the key value storage
-pub struct SledKeyValueStorage {
-    database: sled::Db,
+mod backend;
+
+#[cfg(feature = "raft")]
+mod raft;
+
+#[cfg(feature = "raft")]
+pub mod raft_proto {
+    tonic::include_proto!("raft");
+}
+
+// Implements the KeyValueStorage gRPC service against any KvBackend, so the
+// RPC layer never has to know which storage engine is behind it. When built
+// with the `raft` feature and constructed via `with_raft`, `insert` and
+// `delete` are proposed through Raft instead of going straight to the
+// backend, so they only complete once a majority of the cluster has
+// committed them. `batch`, `drop_store`, `set_store_quota` and
+// `repair_store_quota` still act on the local backend only.
+pub struct KeyValueStorageService<B: KvBackend> {
+    backend: B,
+    #[cfg(feature = "raft")]
+    raft: Option<Arc<raft::RaftNode>>,
+}
+
+impl<B: KvBackend> KeyValueStorageService<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            #[cfg(feature = "raft")]
+            raft: None,
+        }
+    }
+
+    #[cfg(feature = "raft")]
+    pub fn with_raft(backend: B, raft: Arc<raft::RaftNode>) -> Self {
+        Self { backend, raft: Some(raft) }
+    }
 }
 
-// Implement key value storage with the sled database
 #[tonic::async_trait]
-impl KeyValueStorage for SledKeyValueStorage {
+impl<B: KvBackend> KeyValueStorage for KeyValueStorageService<B> {
+    type ScanStream = Pin<Box<dyn Stream<Item = Result<KeyValuePair, Status>> + Send>>;
+
     // Asynchronously find a value by its key in the storage.
     async fn find_by_key(&self, request: Request<KeyRequest>) -> Result<Response<KeyValuePair>, Status> {
-        let key = request.into_inner().key;
+        let KeyRequest { store, key } = request.into_inner();
 
-        // Attempt to retrieve the value from the database using the key.
-        match self.database.get(&key) {
+        // Attempt to retrieve the value from the backend using the key.
+        match self.backend.get(&store, &key).await {
             // If the key is found and a value is returned...
-            Ok(Some(value_bytes)) => {
-                let key_value_pair = KeyValuePair { key, value: value_bytes.to_vec() };
+            Ok(Some(value)) => {
+                let key_value_pair = KeyValuePair { store, key, value };
                 Ok(Response::new(key_value_pair))
             }
-            // If the key is not found in the database, return a "not found" error.
+            // If the key is not found in the backend, return a "not found" error.
             Ok(None) => Err(Status::not_found("No entry found for the specified key")),
-            // If there is an error accessing the database, return an internal error.
+            // If there is an error accessing the backend, return an internal error.
             Err(_) => Err(Status::internal("Error occurred fetching the key from the database")),
         }
     }
@@ -37,8 +82,16 @@ impl KeyValueStorage for SledKeyValueStorage {
     async fn delete(&self, request: Request<KeyRequest>) -> Result<Response<KeyRequest>, Status> {
         let key_request = request.into_inner();
 
-        // Attempt to remove the key-value pair from the database.
-        match self.database.remove(&key_request.key) {
+        #[cfg(feature = "raft")]
+        if let Some(raft) = &self.raft {
+            return match raft.delete(key_request.store.clone(), key_request.key.clone()).await {
+                Ok(_) => Ok(Response::new(key_request)),
+                Err(error) => Err(Status::unavailable(error.to_string())),
+            };
+        }
+
+        // Attempt to remove the key-value pair from the backend.
+        match self.backend.remove(&key_request.store, &key_request.key).await {
             // If successful, return the original key request in the response.
             Ok(_) => Ok(Response::new(key_request)),
             // If there is an error during deletion, return an internal error.
@@ -48,16 +101,35 @@ impl KeyValueStorage for SledKeyValueStorage {
 
     // Asynchronously insert a key-value pair into the storage.
     async fn insert(&self, request: Request<KeyValuePair>) -> Result<Response<KeyValuePair>, Status> {
-        let KeyValuePair { key, value } = request.into_inner();
-        let value_clone = value.clone();
+        let KeyValuePair { store, key, value } = request.into_inner();
+
+        #[cfg(feature = "raft")]
+        if let Some(raft) = &self.raft {
+            return match raft.insert(store.clone(), key.clone(), value.clone()).await {
+                Ok(response) if response.quota_exceeded => {
+                    let (usage, quota) = self.backend.usage(&store).await.unwrap_or_default();
+                    Err(Status::resource_exhausted(format!(
+                        "store '{store}' quota exceeded (usage: {} bytes / {} keys, quota: {:?} bytes / {:?} keys)",
+                        usage.bytes, usage.keys, quota.max_bytes, quota.max_keys
+                    )))
+                }
+                Ok(_) => Ok(Response::new(KeyValuePair { store, key, value })),
+                Err(error) => Err(Status::unavailable(error.to_string())),
+            };
+        }
 
-        // Attempt to insert the key-value pair into the database.
-        match self.database.insert(&key, value) {
+        // Attempt to insert the key-value pair into the backend.
+        match self.backend.insert(&store, &key, &value).await {
             // If successful, return the original key-value pair in the response.
-            Ok(_) => {
-                let inserted_pair = KeyValuePair { key, value: value_clone };
+            Ok(InsertOutcome::Inserted) => {
+                let inserted_pair = KeyValuePair { store, key, value };
                 Ok(Response::new(inserted_pair))
             }
+            // If applying the insert would push the store over its quota, reject it.
+            Ok(InsertOutcome::QuotaExceeded { usage, quota }) => Err(Status::resource_exhausted(format!(
+                "store '{store}' quota exceeded (usage: {} bytes / {} keys, quota: {:?} bytes / {:?} keys)",
+                usage.bytes, usage.keys, quota.max_bytes, quota.max_keys
+            ))),
             // If there is an error during insertion, return an internal error.
             Err(_) => Err(Status::internal("Error occurred inserting the key-value pair into the database")),
         }
@@ -65,21 +137,22 @@ impl KeyValueStorage for SledKeyValueStorage {
 
     // Asynchronously retrieve all keys that match a certain prefix.
     async fn keys(&self, request: Request<KeysRequest>) -> Result<Response<KeysResponse>, Status> {
-        let prefix = request.into_inner().prefix;
+        let KeysRequest { store, prefix } = request.into_inner();
 
         let mut keys = Vec::new();
-        // Iterate over all key-value pairs in the database that match the prefix.
-        for key_result in self.database.scan_prefix(&prefix) {
-            match key_result {
+        // Iterate over all key-value pairs in the backend that match the prefix.
+        let mut scan = self.backend.scan(&store, prefix.clone(), prefix, true, ScanDirection::Forward, 0).await;
+        while let Some(entry) = scan.next().await {
+            match entry {
                 // If a key is found...
                 Ok((key_bytes, _)) => {
-                    // Convert the binary key (IVec) to a UTF-8 string and push it to the keys vector.
-                    match String::from_utf8(key_bytes.to_vec()) {
+                    // Convert the binary key to a UTF-8 string and push it to the keys vector.
+                    match String::from_utf8(key_bytes) {
                         Ok(key_string) => keys.push(key_string),
                         Err(_) => return Err(Status::internal("Key found is not valid UTF-8")),
                     }
                 },
-                // If there is an error during the database scan, return an internal error.
+                // If there is an error during the backend scan, return an internal error.
                 Err(_) => return Err(Status::internal("Database error occurred during key scan")),
             }
         }
@@ -88,15 +161,120 @@ impl KeyValueStorage for SledKeyValueStorage {
         let response = KeysResponse { keys };
         Ok(Response::new(response))
     }
+
+    // Stream key-value pairs starting at a key, optionally bounded to a prefix,
+    // lazily driven from the backend so large ranges never have to be
+    // materialized in memory.
+    async fn scan(&self, request: Request<ScanRequest>) -> Result<Response<Self::ScanStream>, Status> {
+        let ScanRequest { store, start_key, prefix, start_inclusive, direction, limit } = request.into_inner();
+        let direction = if direction == Direction::Reverse as i32 { ScanDirection::Reverse } else { ScanDirection::Forward };
+
+        let scan = self.backend.scan(&store, start_key, prefix, start_inclusive, direction, limit as usize).await;
+        let mapped = scan.map(move |entry| match entry {
+            Ok((key, value)) => Ok(KeyValuePair { store: store.clone(), key, value }),
+            Err(_) => Err(Status::internal("Database error occurred during scan")),
+        });
+
+        Ok(Response::new(Box::pin(mapped)))
+    }
+
+    // Enumerate every named store that currently holds at least one key.
+    async fn list_stores(&self, _request: Request<ListStoresRequest>) -> Result<Response<ListStoresResponse>, Status> {
+        match self.backend.list_stores().await {
+            Ok(stores) => Ok(Response::new(ListStoresResponse { stores })),
+            Err(_) => Err(Status::internal("Error occurred listing stores")),
+        }
+    }
+
+    // Delete an entire named store and all of its keys.
+    async fn drop_store(&self, request: Request<DropStoreRequest>) -> Result<Response<DropStoreResponse>, Status> {
+        let store = request.into_inner().store;
+
+        match self.backend.drop_store(&store).await {
+            Ok(_) => Ok(Response::new(DropStoreResponse {})),
+            Err(_) => Err(Status::internal("Error occurred dropping the store")),
+        }
+    }
+
+    // Apply a list of inserts/deletes atomically, optionally gated by a
+    // compare-and-swap precondition per key.
+    async fn batch(&self, request: Request<BatchRequest>) -> Result<Response<BatchResponse>, Status> {
+        let BatchRequest { store, operations } = request.into_inner();
+
+        let operations = operations
+            .into_iter()
+            .map(|operation| BatchOperation {
+                key: operation.key,
+                value: operation.value,
+                precondition: operation.precondition.map(|precondition| precondition.expected_value),
+            })
+            .collect();
+
+        match self.backend.batch(&store, operations).await {
+            // If applying the batch would push the store over its quota, reject it.
+            Ok(outcome) if outcome.quota_exceeded.is_some() => {
+                let (usage, quota) = outcome.quota_exceeded.expect("checked above");
+                Err(Status::resource_exhausted(format!(
+                    "store '{store}' quota exceeded (usage: {} bytes / {} keys, quota: {:?} bytes / {:?} keys)",
+                    usage.bytes, usage.keys, quota.max_bytes, quota.max_keys
+                )))
+            }
+            Ok(outcome) => {
+                let results = outcome
+                    .results
+                    .into_iter()
+                    .map(|result| BatchOperationResult { applied: result.applied, current_value: result.current_value })
+                    .collect();
+                Ok(Response::new(BatchResponse { applied: outcome.applied, results }))
+            }
+            Err(_) => Err(Status::internal("Error occurred applying the batch")),
+        }
+    }
+
+    // Configure the max total bytes and/or max key count allowed in a store.
+    async fn set_store_quota(&self, request: Request<SetStoreQuotaRequest>) -> Result<Response<SetStoreQuotaResponse>, Status> {
+        let SetStoreQuotaRequest { store, max_bytes, max_keys } = request.into_inner();
+        self.backend.set_quota(&store, Quota { max_bytes, max_keys }).await;
+        Ok(Response::new(SetStoreQuotaResponse {}))
+    }
+
+    // Report a store's current usage against its configured quota.
+    async fn stats(&self, request: Request<StatsRequest>) -> Result<Response<StoreStats>, Status> {
+        let store = request.into_inner().store;
+
+        match self.backend.usage(&store).await {
+            Ok((usage, quota)) => Ok(Response::new(StoreStats {
+                store,
+                bytes: usage.bytes,
+                keys: usage.keys,
+                max_bytes: quota.max_bytes,
+                max_keys: quota.max_keys,
+            })),
+            Err(_) => Err(Status::internal("Error occurred reading store stats")),
+        }
+    }
+
+    // Recompute a store's usage counters from scratch by scanning its
+    // contents, correcting for any drift.
+    async fn repair_store_quota(&self, request: Request<RepairStoreQuotaRequest>) -> Result<Response<StoreStats>, Status> {
+        let store = request.into_inner().store;
+
+        match self.backend.repair_quota(&store).await {
+            Ok(usage) => {
+                let (_, quota) = self.backend.usage(&store).await.unwrap_or_default();
+                Ok(Response::new(StoreStats { store, bytes: usage.bytes, keys: usage.keys, max_bytes: quota.max_bytes, max_keys: quota.max_keys }))
+            }
+            Err(_) => Err(Status::internal("Error occurred repairing store quota")),
+        }
+    }
 }
 
+#[cfg(not(feature = "raft"))]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let database_name = "database";
     let address = "[::1]:10522".parse()?;
-    let service = SledKeyValueStorage {
-        database: sled::open(database_name).unwrap()
-    };
+    let service = KeyValueStorageService::new(SledBackend::new(sled::open(database_name).unwrap()));
     println!("Listening on 10522...");
     Server::builder()
         .add_service(KeyValueStorageServer::new(service))
@@ -104,3 +282,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .await?;
     Ok(())
 }
+
+// With the `raft` feature, a `RAFT_NODE_ID`/`RAFT_MEMBERS` environment pair
+// switches the server into replicated mode: inserts and deletes are proposed
+// through openraft and only acknowledged once committed, and the cluster's internal
+// `append_entries`/`vote`/`install_snapshot` RPCs are served alongside the
+// regular KeyValueStorage service.
+#[cfg(feature = "raft")]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::Arc;
+
+    let database_name = "database";
+    let address = "[::1]:10522".parse()?;
+    let database = sled::open(database_name).unwrap();
+    let backend = Arc::new(SledBackend::new(database.clone()));
+
+    let node_id: raft::NodeId = std::env::var("RAFT_NODE_ID")?.parse()?;
+    let members = raft::parse_members(&std::env::var("RAFT_MEMBERS")?)?;
+    let log_store = raft::LogStore::new(&database)?;
+    let state_machine = raft::StateMachineStore::new(&database, backend.clone())?;
+
+    let raft_instance = openraft::Raft::new(
+        node_id,
+        Arc::new(openraft::Config::default().validate()?),
+        raft::RaftNetworkFactoryImpl,
+        log_store,
+        state_machine,
+    )
+    .await?;
+
+    // Seeds the cluster's membership the first time any node boots; once the
+    // cluster has been initialized this errors on every later restart, which
+    // is expected and safe to ignore.
+    let _ = raft_instance.initialize(members).await;
+
+    let raft_node = Arc::new(raft::RaftNode::new(raft_instance.clone(), backend.clone()));
+    let service = KeyValueStorageService::with_raft(SledBackend::new(database), raft_node);
+    let raft_internal = raft::RaftInternalService::new(raft_instance);
+
+    println!("Listening on 10522 (raft node {node_id})...");
+    Server::builder()
+        .add_service(KeyValueStorageServer::new(service))
+        .add_service(raft_proto::raft_internal_server::RaftInternalServer::new(raft_internal))
+        .serve(address)
+        .await?;
+    Ok(())
+}